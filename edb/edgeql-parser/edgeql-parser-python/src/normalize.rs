@@ -1,4 +1,5 @@
 use std::collections::BTreeSet;
+use std::collections::HashMap;
 
 use edgeql_parser::tokenizer::{Kind, Tokenizer, Token, Value};
 use edgeql_parser::position::{Pos, Span};
@@ -7,27 +8,99 @@ use blake2::{Blake2b512, Digest};
 
 #[derive(Debug, PartialEq)]
 pub struct Variable {
-    pub value: Value,
+    pub value: VarValue,
+}
+
+/// The decoded value of an extracted parameter. A scalar constant carries its
+/// tokenizer `Value` directly; a collapsed array literal carries the element
+/// values, since the base `edgeql-parser` `Value` has no array variant. The
+/// Python consumer decodes the two cases separately.
+#[derive(Debug, PartialEq)]
+pub enum VarValue {
+    Scalar(Value),
+    Array(Vec<Value>),
 }
 
 pub struct Entry<'a> {
     pub processed_source: String,
     pub hash: [u8; 64],
     pub tokens: Vec<Token<'a>>,
+    /// Per-token provenance, aligned 1:1 with `tokens`. `Token` lives in the
+    /// sibling `edgeql-parser` crate and cannot carry extra fields, so the
+    /// synthetic/user distinction is kept alongside it here. Consumers
+    /// building a source map from `processed_source` use this to tell a
+    /// normalizer-injected token from real user text.
+    pub origins: Vec<Origin>,
     pub variables: Vec<Vec<Variable>>,
     pub end_pos: Pos,
     pub named_args: bool,
     pub first_arg: Option<usize>,
 }
 
-#[derive(Debug)]
-pub enum Error {
-    Tokenizer(String, Pos),
-    Assertion(String, Pos),
+/// Where a token in `Entry::tokens` came from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Origin {
+    /// Copied verbatim from the user-written source.
+    UserSource,
+    /// Fabricated by the normalizer, replacing the constant that spanned
+    /// `replaces` in the original source.
+    Injected { replaces: Span },
 }
 
-fn push_var<'x>(res: &mut Vec<Token<'x>>, module: &'x str, typ: &'x str,
-    var: String, span: Span)
+/// A single span annotated with an explanatory message, used to build
+/// multi-span diagnostics.
+#[derive(Debug, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A structured diagnostic: a human-readable summary, a primary labeled span
+/// pointing at the offending source, zero or more secondary labels giving
+/// related context, and an optional machine-applicable suggestion.
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    pub message: String,
+    pub primary: Label,
+    pub secondary: Vec<Label>,
+    pub suggestion: Option<String>,
+}
+
+impl Error {
+    fn new(message: impl Into<String>, span: Span, label: impl Into<String>)
+        -> Error
+    {
+        Error {
+            message: message.into(),
+            primary: Label { span, message: label.into() },
+            secondary: Vec::new(),
+            suggestion: None,
+        }
+    }
+
+    fn with_secondary(mut self, span: Span, message: impl Into<String>)
+        -> Error
+    {
+        self.secondary.push(Label { span, message: message.into() });
+        self
+    }
+}
+
+/// Outcome of scanning a statement's `$`-arguments.
+#[derive(Debug, PartialEq)]
+enum Vars {
+    /// `(named_args, next_var_index)` for a statement that only uses one of
+    /// the two argument styles.
+    Found(bool, usize),
+    /// A named argument appears alongside a positional one, carrying the spans
+    /// of the first offender of each style for a multi-span diagnostic.
+    Mixed { named: Span, positional: Span },
+    /// The positional index space would overflow; leave the query untouched.
+    TooMany,
+}
+
+fn push_var<'x>(res: &mut Vec<Token<'x>>, origins: &mut Vec<Origin>,
+    module: &'x str, typ: &'x str, var: String, span: Span)
 {
     res.push(Token {kind: Kind::OpenParen, text: "(".into(), span, value: None});
     res.push(Token {kind: Kind::Less, text: "<".into(), span, value: None});
@@ -39,34 +112,119 @@ fn push_var<'x>(res: &mut Vec<Token<'x>>, module: &'x str, typ: &'x str,
     res.push(Token {kind: Kind::Greater, text: ">".into(), span, value: None});
     res.push(Token {kind: Kind::Argument, text: var.into(), span, value: None});
     res.push(Token {kind: Kind::CloseParen, text: ")".into(), span, value: None});
+    // All eight fabricated tokens replace the single original constant.
+    origins.extend(std::iter::repeat(Origin::Injected { replaces: span })
+        .take(8));
 }
 
-fn scan_vars<'x, 'y: 'x, I>(tokens: I) -> Option<(bool, usize)>
+fn scan_vars<'x, 'y: 'x, I>(tokens: I) -> Vars
     where I: IntoIterator<Item=&'x Token<'y>>,
 {
     let mut max_visited = None::<usize>;
     let mut names = BTreeSet::new();
+    let mut first_positional = None::<Span>;
+    let mut first_named = None::<Span>;
     for t in tokens {
         if t.kind == Kind::Argument {
             if let Ok(v) = t.text[1..].parse() {
+                if first_positional.is_none() {
+                    first_positional = Some(t.span);
+                }
                 if max_visited.map(|old| v > old).unwrap_or(true) {
                     max_visited = Some(v);
                 }
             } else {
+                if first_named.is_none() {
+                    first_named = Some(t.span);
+                }
                 names.insert(&t.text[..]);
             }
         }
     }
+    if !names.is_empty() && max_visited.is_some() {
+        // mixed arguments: report both offenders
+        return Vars::Mixed {
+            named: first_named.unwrap(),
+            positional: first_positional.unwrap(),
+        };
+    }
     if names.is_empty() {
-        let next = max_visited.map(|x| x.checked_add(1)).unwrap_or(Some(0))?;
-        Some((false, next))
-    } else if max_visited.is_some() {
-        return None  // mixed arguments
+        match max_visited.map(|x| x.checked_add(1)).unwrap_or(Some(0)) {
+            Some(next) => Vars::Found(false, next),
+            None => Vars::TooMany,
+        }
     } else {
-        Some((true, names.len()))
+        Vars::Found(true, names.len())
+    }
+}
+
+/// Whether `kind` is a token that produces a value, so that a following `[`
+/// opens a subscript/index rather than an array literal.
+fn is_value_token(kind: Kind) -> bool {
+    matches!(kind,
+        Kind::Ident | Kind::BacktickName
+        | Kind::CloseParen | Kind::CloseBracket | Kind::CloseBrace
+        | Kind::Str | Kind::BinStr | Kind::Argument
+        | Kind::IntConst | Kind::FloatConst
+        | Kind::BigIntConst | Kind::DecimalConst)
+}
+
+/// The `array<T>` type name for a homogeneous list of elements of `kind`, or
+/// `None` if that kind of constant is not one we extract.
+fn array_type(kind: Kind) -> Option<&'static str> {
+    match kind {
+        Kind::IntConst => Some("array<int64>"),
+        Kind::FloatConst => Some("array<float64>"),
+        Kind::BigIntConst => Some("array<bigint>"),
+        Kind::DecimalConst => Some("array<decimal>"),
+        Kind::Str => Some("array<str>"),
+        Kind::BinStr => Some("array<bytes>"),
+        _ => None,
     }
 }
 
+/// Try to recognize a bracketed, comma-separated run of constants all of one
+/// extractable kind. `tokens` must start at the `OpenBracket`. On success
+/// returns the `array<T>` type name, the collected element values, and the
+/// index (relative to `tokens`) of the matching `CloseBracket`. Returns `None`
+/// for an empty list, mixed element kinds, a nested bracket, or a trailing
+/// comma — in which case the caller leaves the bracket inline.
+fn scan_array(tokens: &[Token<'_>]) -> Option<(&'static str, Vec<Value>, usize)>
+{
+    let mut elem_kind = None::<Kind>;
+    let mut values = Vec::new();
+    let mut expect_value = true;
+    let mut idx = 1;
+    while idx < tokens.len() {
+        let t = &tokens[idx];
+        match t.kind {
+            Kind::CloseBracket => {
+                if expect_value || values.is_empty() {
+                    return None;
+                }
+                return array_type(elem_kind?).map(|typ| (typ, values, idx));
+            }
+            Kind::Comma if !expect_value => {
+                expect_value = true;
+            }
+            k if expect_value && array_type(k).is_some()
+                && t.text != "9223372036854775808" =>
+            {
+                match elem_kind {
+                    None => elem_kind = Some(k),
+                    Some(ek) if ek == k => {}
+                    Some(_) => return None, // mixed element kinds
+                }
+                values.push(t.value.clone()?);
+                expect_value = false;
+            }
+            _ => return None,
+        }
+        idx += 1;
+    }
+    None
+}
+
 fn hash(text: &str) -> [u8; 64] {
     let mut result = [0u8; 64];
     result.copy_from_slice(&Blake2b512::new_with_prefix(text.as_bytes())
@@ -78,16 +236,17 @@ pub fn normalize(text: &str) -> Result<Entry, Error> {
     let mut token_stream = Tokenizer::new(text).validated_values();
     let tokens = (&mut token_stream)
         .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| Error::Tokenizer(e.message, e.span.start))?;
+        .map_err(|e| Error::new(e.message, e.span, "invalid token"))?;
     let end_pos = token_stream.current_pos();
     let (named_args, var_idx) = match scan_vars(&tokens) {
-        Some(pair) => pair,
-        None => {
+        Vars::Found(named_args, var_idx) => (named_args, var_idx),
+        Vars::TooMany => {
             // don't extract from invalid query, let python code do its work
             let processed_source = serialize_tokens(&tokens);
             return Ok(Entry {
                 hash: hash(&processed_source),
                 processed_source,
+                origins: vec![Origin::UserSource; tokens.len()],
                 tokens,
                 variables: Vec::new(),
                 end_pos,
@@ -95,22 +254,61 @@ pub fn normalize(text: &str) -> Result<Entry, Error> {
                 first_arg: None,
             });
         }
+        Vars::Mixed { named, positional } => {
+            return Err(Error::new(
+                "cannot combine named and positional arguments",
+                named, "named argument used here")
+                .with_secondary(positional, "first positional argument here"));
+        }
     };
     let mut rewritten_tokens = Vec::with_capacity(tokens.len());
+    let mut origins = Vec::with_capacity(tokens.len());
     let mut all_variables = Vec::new();
     let mut variables = Vec::new();
     let mut counter = var_idx;
-    let mut next_var = || {
-        let n = counter;
-        counter += 1;
-        if named_args {
-            format!("$__edb_arg_{}", n)
+    // Within a single statement, fold structurally identical constants onto a
+    // single parameter so that e.g. `.a = 1 AND .b = 1` produces one argument.
+    // Only the numeric/positional path is eligible: named-arg rewriting must
+    // keep a stable one-variable-per-occurrence mapping.
+    let mut dedup = HashMap::<(Kind, String), usize>::new();
+    let emit_const = |rewritten: &mut Vec<Token<'_>>,
+                      origins: &mut Vec<Origin>,
+                      vars: &mut Vec<Variable>,
+                      counter: &mut usize,
+                      dedup: &mut HashMap<(Kind, String), usize>,
+                      typ: &'static str,
+                      kind: Kind,
+                      value: VarValue,
+                      span: Span| {
+        if !named_args {
+            let key = (kind, format!("{:?}", value));
+            if let Some(&n) = dedup.get(&key) {
+                push_var(rewritten, origins, "__std__", typ,
+                    format!("${}", n), span);
+                return;
+            }
+            let n = *counter;
+            *counter += 1;
+            push_var(rewritten, origins, "__std__", typ,
+                format!("${}", n), span);
+            vars.push(Variable { value });
+            dedup.insert(key, n);
         } else {
-            format!("${}", n)
+            let n = *counter;
+            *counter += 1;
+            push_var(rewritten, origins, "__std__", typ,
+                format!("$__edb_arg_{}", n), span);
+            vars.push(Variable { value });
         }
     };
     let mut last_was_set = false;
+    // When an array literal is collapsed into a single parameter, its interior
+    // tokens are consumed here; `skip_to` is the index to resume scanning from.
+    let mut skip_to = 0;
     for (idx, tok) in tokens.iter().enumerate() {
+        if idx < skip_to {
+            continue;
+        }
         let mut is_set = false;
         match tok.kind {
             Kind::IntConst
@@ -124,50 +322,72 @@ pub fn normalize(text: &str) -> Result<Entry, Error> {
                     if text.eq_ignore_ascii_case("LIMIT")))
             && tok.text != "9223372036854775808"
             => {
-                push_var(&mut rewritten_tokens, "__std__", "int64",
-                    next_var(),
-                    tok.span);
-                variables.push(Variable {
-                    value: tok.value.clone().unwrap(),
-                });
+                emit_const(&mut rewritten_tokens, &mut origins, &mut variables,
+                    &mut counter, &mut dedup, "int64", Kind::IntConst,
+                    VarValue::Scalar(tok.value.clone().unwrap()), tok.span);
                 continue;
             }
             Kind::FloatConst => {
-                push_var(&mut rewritten_tokens, "__std__", "float64",
-                    next_var(),
-                    tok.span);
-                variables.push(Variable {
-                    value: tok.value.clone().unwrap(),
-                });
+                emit_const(&mut rewritten_tokens, &mut origins, &mut variables,
+                    &mut counter, &mut dedup, "float64", Kind::FloatConst,
+                    VarValue::Scalar(tok.value.clone().unwrap()), tok.span);
                 continue;
             }
             Kind::BigIntConst => {
-                push_var(&mut rewritten_tokens, "__std__", "bigint",
-                    next_var(),
-                    tok.span);
-                variables.push(Variable {
-                    value: tok.value.clone().unwrap(),
-                });
+                emit_const(&mut rewritten_tokens, &mut origins, &mut variables,
+                    &mut counter, &mut dedup, "bigint", Kind::BigIntConst,
+                    VarValue::Scalar(tok.value.clone().unwrap()), tok.span);
                 continue;
             }
             Kind::DecimalConst => {
-                push_var(&mut rewritten_tokens, "__std__", "decimal",
-                    next_var(),
-                    tok.span);
-                variables.push(Variable {
-                    value: tok.value.clone().unwrap(),
-                });
+                emit_const(&mut rewritten_tokens, &mut origins, &mut variables,
+                    &mut counter, &mut dedup, "decimal", Kind::DecimalConst,
+                    VarValue::Scalar(tok.value.clone().unwrap()), tok.span);
                 continue;
             }
             Kind::Str => {
-                push_var(&mut rewritten_tokens, "__std__", "str",
-                    next_var(),
-                    tok.span);
-                variables.push(Variable {
-                    value: tok.value.clone().unwrap(),
-                });
+                emit_const(&mut rewritten_tokens, &mut origins, &mut variables,
+                    &mut counter, &mut dedup, "str", Kind::Str,
+                    VarValue::Scalar(tok.value.clone().unwrap()), tok.span);
+                continue;
+            }
+            Kind::BinStr => {
+                emit_const(&mut rewritten_tokens, &mut origins, &mut variables,
+                    &mut counter, &mut dedup, "bytes", Kind::BinStr,
+                    VarValue::Scalar(tok.value.clone().unwrap()), tok.span);
                 continue;
             }
+            // Collapse a homogeneous list of constants — `[1, 2, 3]` — into a
+            // single `array<T>` parameter so that `.id IN [...]` caches across
+            // differently-sized lists. We buffer the interior and only commit
+            // the rewrite once the closing bracket confirms homogeneity;
+            // anything unexpected (empty list, mixed kinds, nested brackets, a
+            // trailing comma) leaves the `[` inline and the interior tokens to
+            // be handled normally.
+            // Only an array *literal* in expression-start position is eligible;
+            // a `[` right after a value-producing token is a subscript/index
+            // (`arr[0]`, `obj['k']`) and must be left alone, mirroring the
+            // `Kind::Dot` guard on the `IntConst` arm.
+            Kind::OpenBracket
+            if !matches!(rewritten_tokens.last(),
+                Some(t) if is_value_token(t.kind)) =>
+            {
+                if let Some((typ, values, close_idx)) =
+                    scan_array(&tokens[idx..])
+                {
+                    let span = Span {
+                        start: tok.span.start,
+                        end: tokens[idx + close_idx].span.end,
+                    };
+                    emit_const(&mut rewritten_tokens, &mut origins,
+                        &mut variables, &mut counter, &mut dedup, typ,
+                        Kind::OpenBracket, VarValue::Array(values), span);
+                    skip_to = idx + close_idx + 1;
+                    continue;
+                }
+                rewritten_tokens.push(tok.clone());
+                origins.push(Origin::UserSource);
+            }
             Kind::Keyword
             if (matches!(&(&tok.text[..].to_uppercase())[..],
                          "CONFIGURE"|"CREATE"|"ALTER"|"DROP"|"START"|"ANALYZE")
@@ -180,6 +400,7 @@ pub fn normalize(text: &str) -> Result<Entry, Error> {
                 return Ok(Entry {
                     hash: hash(&processed_source),
                     processed_source,
+                    origins: vec![Origin::UserSource; tokens.len()],
                     tokens,
                     variables: Vec::new(),
                     end_pos,
@@ -195,15 +416,21 @@ pub fn normalize(text: &str) -> Result<Entry, Error> {
                 if idx + 1 < tokens.len() {
                     all_variables.push(variables);
                     variables = Vec::new();
+                    dedup.clear();
                 }
                 rewritten_tokens.push(tok.clone());
+                origins.push(Origin::UserSource);
             }
             Kind::Keyword
             if (matches!(&(&tok.text[..].to_uppercase())[..], "SET")) => {
                 is_set = true;
                 rewritten_tokens.push(tok.clone());
+                origins.push(Origin::UserSource);
+            }
+            _ => {
+                rewritten_tokens.push(tok.clone());
+                origins.push(Origin::UserSource);
             }
-            _ => rewritten_tokens.push(tok.clone()),
         }
         last_was_set = is_set;
     }
@@ -216,86 +443,141 @@ pub fn normalize(text: &str) -> Result<Entry, Error> {
         named_args,
         first_arg: if counter <= var_idx { None } else { Some(var_idx) },
         tokens: rewritten_tokens,
+        origins,
         variables: all_variables,
         end_pos,
     });
 }
 
-fn is_operator(token: &Token) -> bool {
-    use edgeql_parser::tokenizer::Kind::*;
+/// A character that can continue an identifier, keyword, or number, and so
+/// would fuse with an adjacent one of the same if not separated.
+fn word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// A character that can start or continue a multi-character operator glyph
+/// (`::`, `:=`, `++`, `//`, `<=`, `.<`, ...). Two of these touching could be
+/// re-lexed as a single longer operator, so they must be kept apart.
+fn operator_char(c: char) -> bool {
+    matches!(c,
+        ':' | '+' | '-' | '*' | '/' | '<' | '>' | '=' | '?'
+        | '.' | '!' | '^' | '&' | '|' | '~' | '@' | '%')
+}
+
+/// Canonical, re-tokenizable text for a single token. String and byte-string
+/// literals are re-emitted from their decoded `Value` with a canonical escape
+/// form rather than echoing the original (possibly differently-quoted) source.
+fn token_text(token: &Token) -> String {
     match token.kind {
-        | Assign
-        | SubAssign
-        | AddAssign
-        | Arrow
-        | Coalesce
-        | Namespace
-        | DoubleSplat
-        | BackwardLink
-        | FloorDiv
-        | Concat
-        | GreaterEq
-        | LessEq
-        | NotEq
-        | NotDistinctFrom
-        | DistinctFrom
-        | Comma
-        | OpenParen
-        | CloseParen
-        | OpenBracket
-        | CloseBracket
-        | OpenBrace
-        | CloseBrace
-        | Dot
-        | Semicolon
-        | Colon
-        | Add
-        | Sub
-        | Mul
-        | Div
-        | Modulo
-        | Pow
-        | Less
-        | Greater
-        | Eq
-        | Ampersand
-        | Pipe
-        | At
-        => true,
-        | DecimalConst
-        | FloatConst
-        | IntConst
-        | BigIntConst
-        | BinStr
-        | Argument
-        | Str
-        | BacktickName
-        | Keyword
-        | Ident
-        | Substitution
-        => false,
+        Kind::Str => match &token.value {
+            Some(Value::String(s)) => quote_string(s),
+            _ => token.text.to_string(),
+        },
+        Kind::BinStr => match &token.value {
+            Some(Value::Bytes(b)) => quote_bytes(b),
+            _ => token.text.to_string(),
+        },
+        _ => token.text.to_string(),
     }
 }
 
-fn serialize_tokens(tokens: &[Token<'_>]) -> String {
-    use edgeql_parser::tokenizer::Kind::Argument;
+fn quote_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('\'');
+    for c in s.chars() {
+        match c {
+            '\'' => out.push_str("\\'"),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('\'');
+    out
+}
+
+fn quote_bytes(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() + 3);
+    out.push_str("b'");
+    for &b in bytes {
+        match b {
+            b'\'' => out.push_str("\\'"),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out.push('\'');
+    out
+}
+
+/// Decide whether a space must separate two already-serialized tokens so that
+/// re-tokenizing the concatenation yields the same token stream. Inserting a
+/// space is always safe; this returns `true` only when omitting it could make
+/// the lexer read the boundary as a different token.
+fn needs_space(prev: &Token, prev_text: &str, cur: &Token, cur_text: &str)
+    -> bool
+{
+    let a = match prev_text.chars().last() {
+        Some(c) => c,
+        None => return false,
+    };
+    let b = match cur_text.chars().next() {
+        Some(c) => c,
+        None => return false,
+    };
+    // Two word-like runs (ident/keyword/number) would fuse into one.
+    if word_char(a) && word_char(b) {
+        return true;
+    }
+    // A word right before a string literal could be re-lexed as its prefix
+    // (`b` + `'x'` -> `b'x'`, `r` + `'x'` -> `r'x'`).
+    if word_char(a) && matches!(cur.kind, Kind::Str | Kind::BinStr) {
+        return true;
+    }
+    // `1` `.` would re-lex as the start of a float literal.
+    if prev.kind == Kind::IntConst && b == '.' {
+        return true;
+    }
+    // Two operator glyphs could combine into a single longer operator
+    // (`:`+`=` -> `:=`, `<`+`<`, `+`+`+` -> `++`, `/`+`/` -> `//`, ...).
+    if operator_char(a) && operator_char(b) {
+        return true;
+    }
+    // Adjacent string/byte literals: keep them visibly distinct.
+    if matches!(prev.kind, Kind::Str | Kind::BinStr)
+        && matches!(cur.kind, Kind::Str | Kind::BinStr)
+    {
+        return true;
+    }
+    false
+}
 
+fn serialize_tokens(tokens: &[Token<'_>]) -> String {
     let mut buf = String::new();
-    let mut needs_space = false;
+    let mut prev: Option<(&Token, String)> = None;
     for token in tokens {
-        if needs_space && !is_operator(token) && token.kind != Argument {
-            buf.push(' ');
+        let text = token_text(token);
+        if let Some((prev_tok, prev_text)) = &prev {
+            if needs_space(prev_tok, prev_text, token, &text) {
+                buf.push(' ');
+            }
         }
-        buf.push_str(&token.text);
-        needs_space = !is_operator(token);
+        buf.push_str(&text);
+        prev = Some((token, text));
     }
-    return buf;
+    buf
 }
 
 #[cfg(test)]
 mod test {
-    use super::scan_vars;
-    use edgeql_parser::tokenizer::{Token, Tokenizer};
+    use super::{scan_array, scan_vars, serialize_tokens, Vars};
+    use edgeql_parser::tokenizer::{Kind, Token, Tokenizer};
 
     fn tokenize<'x>(s: &'x str) -> Vec<Token<'x>> {
         let mut r = Vec::new();
@@ -310,34 +592,136 @@ mod test {
         return r;
     }
 
+    fn tokenize_validated<'x>(s: &'x str) -> Vec<Token<'x>> {
+        let mut stream = Tokenizer::new(s).validated_values();
+        let r = (&mut stream)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|e| panic!("Parse error: {}", e.message));
+        r
+    }
+
+    fn kinds(tokens: &[Token]) -> Vec<Kind> {
+        tokens.iter().map(|t| t.kind).collect()
+    }
+
+    // Property: tokenizing, serializing, and re-tokenizing the output must
+    // yield the same `Kind` sequence — the serializer never fuses or splits
+    // adjacent tokens.
+    #[test]
+    fn serialize_round_trips() {
+        let samples = [
+            "SELECT 1+1",
+            "SELECT 1 2 3",
+            "SELECT a b c",
+            "SELECT User FILTER .id IN [1, 2, 3]",
+            "SELECT <int64>$0",
+            "SELECT .a := 1",
+            "SELECT 1 // 2",
+            "SELECT a ++ b",
+            "SELECT <<foo>>",
+            "SELECT (1).0",
+            "SELECT 'hello' ++ 'world'",
+            "SELECT b'abc'",
+            "WITH x := 1 SELECT x",
+            "SELECT count(User) ?? 0",
+        ];
+        for src in samples {
+            let original = tokenize_validated(src);
+            let out = serialize_tokens(&original);
+            let reparsed = tokenize_validated(&out);
+            assert_eq!(kinds(&original), kinds(&reparsed),
+                "round-trip mismatch for {:?} -> {:?}", src, out);
+        }
+    }
+
+    // String and byte literals are re-emitted in a canonical escaped form that
+    // itself re-tokenizes to the same literal value.
+    #[test]
+    fn serialize_canonicalizes_strings() {
+        for src in ["SELECT \"quoted\"", "SELECT 'a\\nb'", "SELECT b'\\x00ff'"] {
+            let original = tokenize_validated(src);
+            let out = serialize_tokens(&original);
+            let reparsed = tokenize_validated(&out);
+            assert_eq!(kinds(&original), kinds(&reparsed));
+            assert_eq!(
+                original.iter().map(|t| t.value.clone()).collect::<Vec<_>>(),
+                reparsed.iter().map(|t| t.value.clone()).collect::<Vec<_>>());
+        }
+    }
+
     #[test]
     fn none() {
-        assert_eq!(scan_vars(&tokenize("SELECT 1+1")).unwrap(), (false, 0));
+        assert_eq!(scan_vars(&tokenize("SELECT 1+1")), Vars::Found(false, 0));
     }
 
     #[test]
     fn numeric() {
-        assert_eq!(scan_vars(&tokenize("$0 $1 $2")).unwrap(), (false, 3));
-        assert_eq!(scan_vars(&tokenize("$2 $3 $2")).unwrap(), (false, 4));
-        assert_eq!(scan_vars(&tokenize("$0 $0 $0")).unwrap(), (false, 1));
-        assert_eq!(scan_vars(&tokenize("$10 $100")).unwrap(), (false, 101));
+        assert_eq!(scan_vars(&tokenize("$0 $1 $2")), Vars::Found(false, 3));
+        assert_eq!(scan_vars(&tokenize("$2 $3 $2")), Vars::Found(false, 4));
+        assert_eq!(scan_vars(&tokenize("$0 $0 $0")), Vars::Found(false, 1));
+        assert_eq!(scan_vars(&tokenize("$10 $100")), Vars::Found(false, 101));
     }
 
     #[test]
     fn named() {
-        assert_eq!(scan_vars(&tokenize("$a")).unwrap(), (true, 1));
-        assert_eq!(scan_vars(&tokenize("$b $c $d")).unwrap(), (true, 3));
-        assert_eq!(scan_vars(&tokenize("$b $c $b")).unwrap(), (true, 2));
-        assert_eq!(scan_vars(&tokenize("$a $b $b $a $c $xx")).unwrap(),
-            (true, 4));
+        assert_eq!(scan_vars(&tokenize("$a")), Vars::Found(true, 1));
+        assert_eq!(scan_vars(&tokenize("$b $c $d")), Vars::Found(true, 3));
+        assert_eq!(scan_vars(&tokenize("$b $c $b")), Vars::Found(true, 2));
+        assert_eq!(scan_vars(&tokenize("$a $b $b $a $c $xx")),
+            Vars::Found(true, 4));
+    }
+
+    #[test]
+    fn array_homogeneous() {
+        let t = tokenize_validated("[1, 2, 3]");
+        let (typ, values, close) = scan_array(&t).unwrap();
+        assert_eq!(typ, "array<int64>");
+        assert_eq!(values.len(), 3);
+        assert_eq!(t[close].kind, Kind::CloseBracket);
+
+        let t = tokenize_validated("['a', 'b']");
+        let (typ, values, _) = scan_array(&t).unwrap();
+        assert_eq!(typ, "array<str>");
+        assert_eq!(values.len(), 2);
+    }
+
+    #[test]
+    fn array_literal_vs_subscript() {
+        use super::normalize;
+        // An array literal in expression position collapses to one parameter.
+        let e = normalize("SELECT User FILTER .id IN [1, 2, 3]").unwrap();
+        assert_eq!(e.variables.iter().flatten().count(), 1);
+        // Subscript/index expressions are left inline (the `[` is not an
+        // array literal); the integer index is extracted as before, but no
+        // `array<...>` parameter is produced.
+        for src in ["SELECT arr[0]", "SELECT obj['k']"] {
+            let e = normalize(src).unwrap();
+            assert!(!e.processed_source.contains("array<"),
+                "{:?} must not be parameterized as an array: {}",
+                src, e.processed_source);
+            assert!(e.processed_source.contains('['),
+                "{:?} should keep its subscript bracket: {}",
+                src, e.processed_source);
+        }
+    }
+
+    #[test]
+    fn array_bails() {
+        // empty, mixed kinds, nested, trailing comma
+        assert!(scan_array(&tokenize_validated("[]")).is_none());
+        assert!(scan_array(&tokenize_validated("[1, 'a']")).is_none());
+        assert!(scan_array(&tokenize_validated("[[1], [2]]")).is_none());
+        assert!(scan_array(&tokenize_validated("[1, 2,]")).is_none());
     }
 
     #[test]
     fn mixed() {
-        assert_eq!(scan_vars(&tokenize("$a $0")), None);
-        assert_eq!(scan_vars(&tokenize("$0 $a")), None);
-        assert_eq!(scan_vars(&tokenize("$b $c $100")), None);
-        assert_eq!(scan_vars(&tokenize("$10 $xx $yy")), None);
+        assert!(matches!(scan_vars(&tokenize("$a $0")), Vars::Mixed { .. }));
+        assert!(matches!(scan_vars(&tokenize("$0 $a")), Vars::Mixed { .. }));
+        assert!(matches!(scan_vars(&tokenize("$b $c $100")),
+            Vars::Mixed { .. }));
+        assert!(matches!(scan_vars(&tokenize("$10 $xx $yy")),
+            Vars::Mixed { .. }));
     }
 
 }